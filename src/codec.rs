@@ -0,0 +1,144 @@
+// Codec identification: the fourcc tags understood by the `<file>:<codec>`
+// suffix (see `parse_stream_arg`), and best-effort detection from a config
+// packet's payload.
+
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    H264,
+    H265,
+    Av1,
+    Opus,
+    Aac,
+}
+
+impl Codec {
+    pub fn fourcc(self) -> [u8; 4] {
+        match self {
+            Codec::H264 => *b"h264",
+            Codec::H265 => *b"h265",
+            Codec::Av1 => *b"av01",
+            Codec::Opus => *b"opus",
+            Codec::Aac => *b"aac ",
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let codec = match s {
+            "h264" => Codec::H264,
+            "h265" => Codec::H265,
+            "av1" => Codec::Av1,
+            "opus" => Codec::Opus,
+            "aac" => Codec::Aac,
+            _ => bail!("Unknown codec: {s} (expected h264, h265, av1, opus or aac)"),
+        };
+        Ok(codec)
+    }
+}
+
+// Best-effort codec detection from a config packet's payload. Audio codecs
+// cannot be reliably distinguished this way, so only video codecs are
+// detected here; callers must specify the codec explicitly for audio
+// streams, or when detection fails.
+pub fn detect(config_payload: &[u8]) -> Option<Codec> {
+    if let Some(nal_header) = first_annexb_nal_header(config_payload) {
+        if nal_header & 0x1F == 7 {
+            // H264 SPS
+            return Some(Codec::H264);
+        }
+        let h265_nal_type = (nal_header >> 1) & 0x3F;
+        if h265_nal_type == 32 || h265_nal_type == 33 {
+            // H265 VPS or SPS
+            return Some(Codec::H265);
+        }
+    }
+
+    if is_av1_sequence_header(config_payload) {
+        return Some(Codec::Av1);
+    }
+
+    None
+}
+
+// Find the first Annex-B start code (00 00 01) and return the byte right
+// after it, which is the NAL unit header for H264/H265.
+fn first_annexb_nal_header(payload: &[u8]) -> Option<u8> {
+    payload
+        .windows(3)
+        .position(|w| w == [0, 0, 1])
+        .and_then(|pos| payload.get(pos + 3))
+        .copied()
+}
+
+// An AV1 OBU header's forbidden bit must be 0, and a sequence header has
+// obu_type == 1.
+fn is_av1_sequence_header(payload: &[u8]) -> bool {
+    let Some(&byte0) = payload.first() else {
+        return false;
+    };
+    if byte0 & 0x80 != 0 {
+        return false;
+    }
+    let obu_type = (byte0 >> 3) & 0x0F;
+    obu_type == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_round_trips_through_cli_names() {
+        // the CLI spelling accepted by `FromStr` does not always match the
+        // fourcc trimmed of padding (e.g. `Av1`'s fourcc is `av01`, not
+        // `av1`), so each codec is paired with its own CLI name here rather
+        // than derived from `fourcc()`.
+        for (codec, name) in [
+            (Codec::H264, "h264"),
+            (Codec::H265, "h265"),
+            (Codec::Av1, "av1"),
+            (Codec::Opus, "opus"),
+            (Codec::Aac, "aac"),
+        ] {
+            assert_eq!(name.parse::<Codec>().unwrap(), codec);
+        }
+    }
+
+    #[test]
+    fn from_str_rejects_unknown_codec() {
+        assert!("vp9".parse::<Codec>().is_err());
+    }
+
+    #[test]
+    fn detect_h264_from_annexb_sps() {
+        // start code, then a NAL header with type 7 (SPS)
+        let payload = [0, 0, 1, 0x67, 0x42, 0x00];
+        assert_eq!(detect(&payload), Some(Codec::H264));
+    }
+
+    #[test]
+    fn detect_h265_from_annexb_vps() {
+        // start code, then a NAL header with type 32 (VPS) in bits 1-6
+        let payload = [0, 0, 1, 32 << 1, 0x01];
+        assert_eq!(detect(&payload), Some(Codec::H265));
+    }
+
+    #[test]
+    fn detect_av1_from_sequence_header_obu() {
+        // forbidden bit clear, obu_type == 1 (sequence header)
+        let payload = [1 << 3];
+        assert_eq!(detect(&payload), Some(Codec::Av1));
+    }
+
+    #[test]
+    fn detect_returns_none_on_unrecognized_payload() {
+        assert_eq!(detect(&[0xFF, 0xFF, 0xFF]), None);
+        assert_eq!(detect(&[]), None);
+    }
+}
@@ -1,29 +1,42 @@
+mod codec;
+mod protocol;
+
 use anyhow::{bail, Result};
 use byteorder::{BigEndian, ByteOrder};
-use std::fs::File;
-use std::io::{BufReader, Read, Write};
-use std::net::{IpAddr, SocketAddr, TcpStream};
+use codec::Codec;
+use protocol::{FramedFileReader, KymuxConn, MetaHeader};
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+// `host` is kept as a string (rather than resolved to an `IpAddr` eagerly)
+// so that hostnames are resolved via DNS at connect time, and re-resolved on
+// every reconnection attempt.
 #[derive(Debug)]
 struct KymuxAddr {
-    addr: SocketAddr,
+    host: String,
+    port: u16,
     endpoint_id: u16,
 }
 
 fn parse_kymux_url(url_str: &str) -> Result<KymuxAddr> {
-    let url = url::Url::parse(&url_str)?;
+    let url = url::Url::parse(url_str)?;
 
     if url.scheme() != "kymux" {
         bail!("Wrong scheme in url: {url}");
     }
 
-    let Some(host) = url.host_str() else {
+    // Use `url.host()` rather than `host_str()`: for an IPv6 address,
+    // `host_str()` keeps the literal's surrounding brackets (e.g. `[::1]`),
+    // which `ToSocketAddrs` does not understand.
+    let Some(host) = url.host() else {
         bail!("Missing host in url: {url}");
     };
-
-    let Ok(ip) = host.parse::<IpAddr>() else {
-        bail!("Invalid IP in url: {url}");
+    let host = match host {
+        url::Host::Domain(domain) => domain.to_string(),
+        url::Host::Ipv4(ip) => ip.to_string(),
+        url::Host::Ipv6(ip) => ip.to_string(),
     };
 
     let Some(port) = url.port() else {
@@ -41,97 +54,693 @@ fn parse_kymux_url(url_str: &str) -> Result<KymuxAddr> {
     };
 
     Ok(KymuxAddr {
-        addr: SocketAddr::new(ip, port),
+        host,
+        port,
         endpoint_id,
     })
 }
 
-fn main() -> Result<()> {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() != 3 {
-        bail!("Syntax error, expected: {} <file> <kymux_url>", args[0]);
+// One track being streamed (e.g. one video track, one audio track).
+//
+// Several streams are interleaved over the same kymux connection, so each
+// one carries its own stream id (`sid`) and codec fourcc.
+struct Stream {
+    file_reader: FramedFileReader,
+    codec: [u8; 4],
+    sid: u8,
+    // the next meta header read from the file, not yet sent (None once the
+    // file is exhausted)
+    pending: Option<MetaHeader>,
+    // the payload bytes for `pending`, read from the file but not yet
+    // successfully written to the socket; kept around so a failed write can
+    // be retried after a reconnect without re-reading (and thus skipping)
+    // the file
+    out_payload: Option<Vec<u8>>,
+    // last config and key frame packets read for sending (cached as soon as
+    // their payload is read, before the send itself), resent after a
+    // reconnect so the decoder on the other end can resynchronize
+    last_config: Option<(MetaHeader, Vec<u8>)>,
+    last_keyframe: Option<(MetaHeader, Vec<u8>)>,
+}
+
+impl Stream {
+    fn open(filepath: &str, codec: Option<Codec>, sid: u8) -> Result<Self> {
+        let file_reader = FramedFileReader::open(filepath)?;
+
+        let mut stream = Self {
+            file_reader,
+            codec: [0; 4],
+            sid,
+            pending: None,
+            out_payload: None,
+            last_config: None,
+            last_keyframe: None,
+        };
+        stream.fetch_next()?;
+        // buffer (and possibly sniff) the first packet's payload before it
+        // is needed for sending, so the codec can be checked right away
+        stream.prepare_out_payload()?;
+
+        let detected = match (stream.pending, &stream.out_payload) {
+            (Some(header), Some(payload)) if header.is_config => codec::detect(payload),
+            _ => None,
+        };
+
+        let resolved = match (codec, detected) {
+            (Some(specified), Some(detected)) if specified != detected => {
+                bail!(
+                    "Codec mismatch for {filepath}: specified {specified:?} but the file contains {detected:?}"
+                );
+            }
+            (Some(specified), _) => specified,
+            (None, Some(detected)) => detected,
+            (None, None) => {
+                bail!("Cannot auto-detect the codec for {filepath}: specify it explicitly as <file>:<codec>");
+            }
+        };
+        stream.codec = resolved.fourcc();
+
+        Ok(stream)
+    }
+
+    // Read the next meta header from the file into `pending`.
+    fn fetch_next(&mut self) -> Result<()> {
+        self.pending = self.file_reader.read_header()?;
+        Ok(())
+    }
+
+    // Skip the pending packet's raw payload without sending it, then fetch
+    // the next meta header.
+    fn skip_pending(&mut self) -> Result<()> {
+        let Some(header) = self.pending else {
+            return Ok(());
+        };
+        // if the payload was already read into `out_payload` (by `open`'s
+        // initial sniff, or by a failed `send_pending` attempt), it is no
+        // longer sitting in the file right after the header: don't skip it
+        // again, or the next header's bytes get consumed instead.
+        if self.out_payload.take().is_none() {
+            self.file_reader.skip_payload(header.size)?;
+        }
+        self.fetch_next()
+    }
+
+    // Fast-forward this stream past every packet that comes before
+    // `target_pts`, stopping on the first config or key frame at or after
+    // it, so the decoder can resynchronize.
+    fn seek_to(&mut self, target_pts: u64) -> Result<()> {
+        loop {
+            let Some(header) = self.pending else {
+                return Ok(());
+            };
+            if header.pts >= target_pts && (header.is_config || header.is_key) {
+                return Ok(());
+            }
+            self.skip_pending()?;
+        }
+    }
+
+    // Read the pending packet's payload into `out_payload`, caching it as the
+    // last config/key frame if applicable. No-op if `out_payload` is already
+    // filled (i.e. a previous send attempt failed partway).
+    fn prepare_out_payload(&mut self) -> Result<()> {
+        if self.out_payload.is_some() {
+            return Ok(());
+        }
+
+        let Some(header) = self.pending else {
+            return Ok(());
+        };
+
+        let payload = self.file_reader.read_payload(header.size)?;
+
+        if header.is_config {
+            self.last_config = Some((header, payload.clone()));
+        }
+        if header.is_key {
+            self.last_keyframe = Some((header, payload.clone()));
+        }
+
+        self.out_payload = Some(payload);
+        Ok(())
+    }
+
+    // Send the pending packet to the connection, then fetch the next one. On
+    // I/O error, `out_payload` is left in place so the caller can retry after
+    // reconnecting.
+    fn send_pending(&mut self, conn: &mut KymuxConn) -> Result<()> {
+        self.prepare_out_payload()?;
+        let (Some(header), Some(payload)) = (self.pending, &self.out_payload) else {
+            return Ok(()); // exhausted
+        };
+
+        conn.send_packet(self.sid, header, payload)?;
+
+        self.out_payload = None;
+        self.fetch_next()
+    }
+
+    // Resend the last config and key frame packets, so a decoder on a newly
+    // (re)established connection can resynchronize.
+    fn resend_recovery(&self, conn: &mut KymuxConn) -> Result<()> {
+        for (header, payload) in [&self.last_config, &self.last_keyframe].into_iter().flatten() {
+            conn.send_packet(self.sid, *header, payload)?;
+        }
+        Ok(())
+    }
+}
+
+// Connect to one kymux endpoint and perform the initial handshake:
+// endpoint id, sync byte, then one codec/sid packet per stream.
+fn try_connect(addr: &KymuxAddr, streams: &[Stream]) -> Result<KymuxConn> {
+    let mut conn = KymuxConn::connect(&addr.host, addr.port)?;
+    conn.handshake(addr.endpoint_id)?;
+
+    for stream in streams {
+        conn.send_codec(stream.sid, stream.codec)?;
     }
 
-    let mut file_reader = {
-        let filepath = &args[1];
-        let file = File::open(filepath)?;
-        BufReader::new(file).take(0)
+    Ok(conn)
+}
+
+// Connect to one of `kymux_addrs`, cycling through them and retrying with
+// exponential backoff (capped) on failure. `url_idx` is updated in place so
+// the next reconnection attempt resumes from where this one left off.
+fn connect_with_retry(
+    kymux_addrs: &[KymuxAddr],
+    url_idx: &mut usize,
+    streams: &[Stream],
+) -> KymuxConn {
+    const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_BACKOFF: Duration = Duration::from_secs(4);
+
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let addr = &kymux_addrs[*url_idx % kymux_addrs.len()];
+        match try_connect(addr, streams) {
+            Ok(conn) => return conn,
+            Err(e) => {
+                eprintln!(
+                    "\nFailed to connect to {}:{} ({e}), retrying in {backoff:?}",
+                    addr.host, addr.port
+                );
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                *url_idx += 1;
+            }
+        }
+    }
+}
+
+// Reconnect after the current connection was lost, and resend every
+// stream's recovery packets so the decoder on the other end can
+// resynchronize. Both steps are retried (cycling through the fallback
+// URLs) until they succeed: a dropped connection during the recovery
+// resend itself is just another transient failure, not a reason to give
+// up and exit.
+fn reconnect_and_recover(
+    kymux_addrs: &[KymuxAddr],
+    url_idx: &mut usize,
+    streams: &[Stream],
+) -> KymuxConn {
+    loop {
+        // the endpoint we were just connected to just failed; cycle to the
+        // next one instead of retrying it first
+        *url_idx += 1;
+        let mut conn = connect_with_retry(kymux_addrs, url_idx, streams);
+
+        let recovered = streams
+            .iter()
+            .all(|stream| stream.resend_recovery(&mut conn).is_ok());
+        if recovered {
+            return conn;
+        }
+        eprintln!("\nConnection lost while resending recovery packets, reconnecting...");
+    }
+}
+
+// Each stream can carry a different codec, so the codec is attached to the
+// individual `<file>:<codec>` stream argument (reusing chunk0-1's per-stream
+// argument) rather than a single global `--codec` flag, which would have no
+// way to apply to more than one stream at a time.
+fn parse_stream_arg(arg: &str, sid: u8) -> Result<Stream> {
+    let (filepath, codec) = match arg.rsplit_once(':') {
+        Some((filepath, codec)) => (filepath, Some(codec.parse()?)),
+        None => (arg, None),
     };
+    Stream::open(filepath, codec, sid)
+}
 
-    let kymux_addr = parse_kymux_url(&args[2])?;
+// Requests accepted on the control socket.
+#[derive(Debug)]
+enum Request {
+    Pause,
+    Resume,
+    Seek { pts_us: u64 },
+    SetSpeed { factor: f32 },
+    Status,
+}
 
-    let mut tcp_stream = TcpStream::connect(kymux_addr.addr)?;
+// The largest request payload currently defined (opcode + Seek's 8-byte
+// pts_us), with a little headroom for future opcodes. Anything claiming to
+// be bigger than this is either a protocol mismatch or corrupt/hostile
+// input, so it is rejected before the length is used to allocate anything.
+const MAX_REQUEST_LEN: usize = 32;
 
-    // The "meta" header length is 12 bytes:
-    // [. . . . . . . .|. . . .]. . . . . . . . . . . . . . . ...
-    //  <-------------> <-----> <-----------------------------...
-    //        PTS        packet        raw packet
-    //                    size
-    //
-    // It is followed by <packet_size> bytes containing the packet/frame.
-    //
-    // The most significant bits of the PTS are used for packet flags:
-    //
-    //  byte 7   byte 6   byte 5   byte 4   byte 3   byte 2   byte 1   byte 0
-    // CK...... ........ ........ ........ ........ ........ ........ ........
-    // ^^<------------------------------------------------------------------->
-    // ||                                PTS
-    // | `- key frame
-    //  `-- config packet
+impl Request {
+    // Read one length-prefixed request from the control socket.
+    fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let mut len_buf = [0; 4];
+        reader.read_exact(&mut len_buf)?;
+        let len = BigEndian::read_u32(&len_buf) as usize;
+        if len > MAX_REQUEST_LEN {
+            bail!("Control request too large: {len} bytes (max {MAX_REQUEST_LEN})");
+        }
 
-    tcp_stream.write(&kymux_addr.endpoint_id.to_be_bytes())?;
+        let mut payload = vec![0; len];
+        reader.read_exact(&mut payload)?;
 
-    tcp_stream.read(&mut [0u8])?; // sync byte
+        let Some(&opcode) = payload.first() else {
+            bail!("Empty control request");
+        };
 
-    let start = Instant::now();
-    let mut pts_origin = None;
+        let request = match opcode {
+            0 => Request::Pause,
+            1 => Request::Resume,
+            2 => {
+                let Some(body) = payload.get(1..9) else {
+                    bail!("Truncated Seek request: expected 8 bytes, got {}", len - 1);
+                };
+                Request::Seek {
+                    pts_us: BigEndian::read_u64(body),
+                }
+            }
+            3 => {
+                let Some(body) = payload.get(1..5) else {
+                    bail!(
+                        "Truncated SetSpeed request: expected 4 bytes, got {}",
+                        len - 1
+                    );
+                };
+                Request::SetSpeed {
+                    factor: BigEndian::read_f32(body),
+                }
+            }
+            4 => Request::Status,
+            opcode => bail!("Unknown control request opcode: {opcode}"),
+        };
+        Ok(request)
+    }
+}
+
+// Replies sent back on the control socket.
+#[derive(Debug)]
+enum Answer {
+    Ok,
+    Status {
+        current_pts: u64,
+        paused: bool,
+        speed: f32,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl Answer {
+    // Write this answer, length-prefixed, to the control socket.
+    fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        let mut payload = Vec::new();
+        match self {
+            Answer::Ok => payload.push(0),
+            Answer::Status {
+                current_pts,
+                paused,
+                speed,
+            } => {
+                payload.push(1);
+                let mut buf = [0; 8];
+                BigEndian::write_u64(&mut buf, *current_pts);
+                payload.extend_from_slice(&buf);
+                payload.push(*paused as u8);
+                let mut buf = [0; 4];
+                BigEndian::write_f32(&mut buf, *speed);
+                payload.extend_from_slice(&buf);
+            }
+            Answer::Error { message } => {
+                payload.push(2);
+                payload.extend_from_slice(message.as_bytes());
+            }
+        }
+
+        let mut len_buf = [0; 4];
+        BigEndian::write_u32(&mut len_buf, payload.len() as u32);
+        writer.write_all(&len_buf)?;
+        writer.write_all(&payload)?;
+        Ok(())
+    }
+}
 
-    let sid_and_codec_packet = [b'h', b'2', b'6', b'4', 0, 0, 0, 0, 0, 0, 0, 0];
-    tcp_stream.write(&sid_and_codec_packet)?;
+// State shared between the control socket thread and the streaming loop.
+struct ControlState {
+    paused: bool,
+    speed: f32,
+    current_pts: u64,
+    seek_to: Option<u64>,
+}
 
+impl ControlState {
+    fn new() -> Self {
+        Self {
+            paused: false,
+            speed: 1.0,
+            current_pts: 0,
+            seek_to: None,
+        }
+    }
+}
+
+// Bind the control Unix socket and handle incoming requests in a background
+// thread for as long as the process runs.
+fn spawn_control_thread(socket_path: &str, control: Arc<Mutex<ControlState>>) -> Result<()> {
+    // remove a stale socket file left over from a previous run
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+
+    std::thread::spawn(move || {
+        for conn in listener.incoming() {
+            let Ok(conn) = conn else {
+                continue;
+            };
+            let control = Arc::clone(&control);
+            std::thread::spawn(move || {
+                let _ = handle_control_conn(conn, &control);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_control_conn(mut conn: UnixStream, control: &Arc<Mutex<ControlState>>) -> Result<()> {
+    loop {
+        let request = match Request::read_from(&mut conn) {
+            Ok(request) => request,
+            Err(_) => return Ok(()), // connection closed
+        };
+
+        let answer = {
+            let mut state = control.lock().unwrap();
+            match request {
+                Request::Pause => {
+                    state.paused = true;
+                    Answer::Ok
+                }
+                Request::Resume => {
+                    state.paused = false;
+                    Answer::Ok
+                }
+                Request::Seek { pts_us } => {
+                    state.seek_to = Some(pts_us);
+                    Answer::Ok
+                }
+                Request::SetSpeed { factor } => {
+                    // `Duration::div_f32` panics on a zero, negative or NaN
+                    // divisor, and `factor` comes straight off the socket.
+                    if factor.is_finite() && factor > 0.0 {
+                        state.speed = factor;
+                        Answer::Ok
+                    } else {
+                        Answer::Error {
+                            message: format!("Invalid speed factor: {factor}"),
+                        }
+                    }
+                }
+                Request::Status => Answer::Status {
+                    current_pts: state.current_pts,
+                    paused: state.paused,
+                    speed: state.speed,
+                },
+            }
+        };
+
+        answer.write_to(&mut conn)?;
+    }
+}
+
+// Apply a pending seek request: fast-forward every stream to `target_pts`
+// and rebase the clock so playback resumes from there immediately.
+fn apply_seek(
+    streams: &mut [Stream],
+    target_pts: u64,
+    start: &mut Instant,
+    pts_origin: &mut Option<u64>,
+) -> Result<()> {
+    for stream in streams.iter_mut() {
+        stream.seek_to(target_pts)?;
+    }
+    *pts_origin = Some(target_pts);
+    *start = Instant::now();
+    Ok(())
+}
+
+// Block while the control state is paused, applying any seek request that
+// arrives in the meantime. On resume, shift `start` by the paused duration
+// so that timing does not jump.
+fn wait_while_paused(
+    control: &Mutex<ControlState>,
+    streams: &mut [Stream],
+    start: &mut Instant,
+    pts_origin: &mut Option<u64>,
+) -> Result<()> {
+    let mut paused_at = Instant::now();
     loop {
-        let mut header = [0; 12];
-        file_reader.set_limit(12);
-        if let Err(_) = file_reader.read_exact(&mut header) {
-            // EOF
+        let (paused, seek_to) = {
+            let mut state = control.lock().unwrap();
+            (state.paused, state.seek_to.take())
+        };
+
+        if let Some(target) = seek_to {
+            apply_seek(streams, target, start, pts_origin)?;
+            // `apply_seek` just rebased `start` to now, so only the
+            // still-paused interval (from this point on) remains to be
+            // compensated for below.
+            paused_at = Instant::now();
+        }
+
+        if !paused {
             break;
         }
 
-        let pts_and_flags = BigEndian::read_u64(&header[..8]);
-        let pts = pts_and_flags & 0x3F_FF_FF_FF_FF_FF_FF_FF;
-        let is_config = pts_and_flags & 0x80_00_00_00_00_00_00_00 != 0;
-        let size = BigEndian::read_u32(&header[8..12]);
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    *start += Instant::now().duration_since(paused_at);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<_> = std::env::args().collect();
+    if args.len() < 4 {
+        bail!(
+            "Syntax error, expected: {} <kymux_url>[,<kymux_url> ...] <control_socket> <file>[:<codec>] [<file>[:<codec>] ...]",
+            args[0]
+        );
+    }
+
+    // the first URL is the primary endpoint, the following ones are
+    // fallbacks used when the primary becomes unreachable
+    let kymux_addrs = args[1]
+        .split(',')
+        .map(parse_kymux_url)
+        .collect::<Result<Vec<_>>>()?;
+    let control_socket_path = &args[2];
+
+    let mut streams = args[3..]
+        .iter()
+        .enumerate()
+        .map(|(sid, arg)| parse_stream_arg(arg, sid as u8))
+        .collect::<Result<Vec<_>>>()?;
+
+    let control = Arc::new(Mutex::new(ControlState::new()));
+    spawn_control_thread(control_socket_path, Arc::clone(&control))?;
+
+    let mut url_idx = 0;
+    let mut conn = connect_with_retry(&kymux_addrs, &mut url_idx, &streams);
+
+    let mut start = Instant::now();
+    let mut pts_origin = None;
+
+    loop {
+        // consult the control state before sending the next packet
+        {
+            let seek_to = {
+                let mut state = control.lock().unwrap();
+                state.seek_to.take()
+            };
+            if let Some(target) = seek_to {
+                apply_seek(&mut streams, target, &mut start, &mut pts_origin)?;
+            }
+            if control.lock().unwrap().paused {
+                wait_while_paused(&control, &mut streams, &mut start, &mut pts_origin)?;
+            }
+        }
+
+        // pick, among all streams still having data, the one whose pending
+        // packet has the smallest PTS
+        let next_idx = streams
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.pending.is_some())
+            .min_by_key(|(_, s)| s.pending.unwrap().pts)
+            .map(|(idx, _)| idx);
 
-        if !is_config {
+        let Some(idx) = next_idx else {
+            // every stream is exhausted
+            break;
+        };
+
+        let header = streams[idx].pending.unwrap();
+
+        if !header.is_config {
             // wait until PTS
             let now = Instant::now();
             let elapsed = now.duration_since(start);
             if let Some(pts_origin) = pts_origin {
-                let target = Duration::from_micros(pts - pts_origin);
+                // `pts_origin` is pinned to the first non-config packet, but
+                // a config packet can carry a PTS larger than the frame that
+                // follows it, so `header.pts` is not guaranteed to be >=
+                // `pts_origin` here; treat that case as "send now" rather
+                // than underflowing.
+                let target = Duration::from_micros(header.pts.saturating_sub(pts_origin));
                 if target > elapsed {
-                    let to_wait = target - elapsed;
+                    let speed = control.lock().unwrap().speed;
+                    let to_wait = (target - elapsed).div_f32(speed);
                     std::thread::sleep(to_wait);
                 }
             } else {
-                pts_origin = Some(pts)
+                pts_origin = Some(header.pts)
             }
         }
 
-        print!("\rStreaming pts={}", pts);
-        let _ = std::io::stdout().flush();
-
-        // header format changed due to config packet
-        header[0] = 0x80 | ((header[0] & 0xC0) >> 1) | (header[0] & 0x1F);
+        control.lock().unwrap().current_pts = header.pts;
 
-        tcp_stream.write(&header)?;
+        print!("\rStreaming sid={} pts={}", streams[idx].sid, header.pts);
+        let _ = std::io::stdout().flush();
 
-        file_reader.set_limit(size as u64);
-        let r = std::io::copy(&mut file_reader, &mut tcp_stream)?;
-        if r < size as u64 {
-            // EOF
-            break;
+        if streams[idx].send_pending(&mut conn).is_err() {
+            eprintln!("\nConnection lost, reconnecting...");
+            conn = reconnect_and_recover(&kymux_addrs, &mut url_idx, &streams);
         }
     }
     println!("\rComplete");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip_request(bytes: &[u8]) -> Request {
+        Request::read_from(&mut std::io::Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn read_from_parses_pause_resume_and_status() {
+        assert!(matches!(roundtrip_request(&[0, 0, 0, 1, 0]), Request::Pause));
+        assert!(matches!(roundtrip_request(&[0, 0, 0, 1, 1]), Request::Resume));
+        assert!(matches!(roundtrip_request(&[0, 0, 0, 1, 4]), Request::Status));
+    }
+
+    #[test]
+    fn read_from_parses_seek() {
+        let mut payload = vec![2];
+        payload.extend_from_slice(&1_234_567u64.to_be_bytes());
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&payload);
+        let Request::Seek { pts_us } = roundtrip_request(&bytes) else {
+            panic!("expected Seek");
+        };
+        assert_eq!(pts_us, 1_234_567);
+    }
+
+    #[test]
+    fn read_from_parses_set_speed() {
+        let mut payload = vec![3];
+        payload.extend_from_slice(&2.5f32.to_be_bytes());
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&payload);
+        let Request::SetSpeed { factor } = roundtrip_request(&bytes) else {
+            panic!("expected SetSpeed");
+        };
+        assert_eq!(factor, 2.5);
+    }
+
+    #[test]
+    fn read_from_rejects_empty_and_truncated_requests() {
+        assert!(Request::read_from(&mut std::io::Cursor::new(&[0, 0, 0, 0])).is_err());
+        // Seek with only 3 of its 8 body bytes
+        assert!(Request::read_from(&mut std::io::Cursor::new(&[0, 0, 0, 4, 2, 0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn read_from_rejects_oversized_length() {
+        let len_buf = (MAX_REQUEST_LEN as u32 + 1).to_be_bytes();
+        assert!(Request::read_from(&mut std::io::Cursor::new(&len_buf)).is_err());
+    }
+
+    fn write_answer(answer: &Answer) -> Vec<u8> {
+        let mut buf = Vec::new();
+        answer.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn write_to_serializes_ok() {
+        let bytes = write_answer(&Answer::Ok);
+        assert_eq!(bytes, [0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn write_to_serializes_status() {
+        let bytes = write_answer(&Answer::Status {
+            current_pts: 42,
+            paused: true,
+            speed: 1.5,
+        });
+        let mut expected = vec![1];
+        expected.extend_from_slice(&42u64.to_be_bytes());
+        expected.push(1);
+        expected.extend_from_slice(&1.5f32.to_be_bytes());
+        assert_eq!(bytes, [&(expected.len() as u32).to_be_bytes()[..], &expected].concat());
+    }
+
+    fn write_frame(file: &mut std::fs::File, pts: u64, is_config: bool, is_key: bool, payload: &[u8]) {
+        let mut flags = pts;
+        if is_config {
+            flags |= 0x80_00_00_00_00_00_00_00;
+        }
+        if is_key {
+            flags |= 0x40_00_00_00_00_00_00_00;
+        }
+        file.write_all(&flags.to_be_bytes()).unwrap();
+        file.write_all(&(payload.len() as u32).to_be_bytes()).unwrap();
+        file.write_all(payload).unwrap();
+    }
+
+    #[test]
+    fn seek_to_fast_forwards_to_the_first_keyframe_at_or_after_target() {
+        let path = std::env::temp_dir().join("rtstreamer_test_seek_to.bin");
+        let mut file = std::fs::File::create(&path).unwrap();
+        write_frame(&mut file, 0, true, false, &[0; 4]); // config, not a seek target
+        write_frame(&mut file, 1000, false, true, &[0; 4]); // keyframe, before target
+        write_frame(&mut file, 2000, false, true, &[0; 4]); // keyframe, at/after target
+        drop(file);
+
+        let mut stream = Stream::open(path.to_str().unwrap(), Some(Codec::H264), 0).unwrap();
+        stream.seek_to(1500).unwrap();
+        assert_eq!(stream.pending.unwrap().pts, 2000);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
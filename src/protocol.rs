@@ -0,0 +1,281 @@
+// The kymux wire protocol and the on-disk meta header format.
+//
+// The "meta" header length is 12 bytes:
+// [. . . . . . . .|. . . .]. . . . . . . . . . . . . . . ...
+//  <-------------> <-----> <-----------------------------...
+//        PTS        packet        raw packet
+//                    size
+//
+// It is followed by <packet_size> bytes containing the packet/frame.
+//
+// The most significant bits of the PTS are used for packet flags:
+//
+//  byte 7   byte 6   byte 5   byte 4   byte 3   byte 2   byte 1   byte 0
+// CK...... ........ ........ ........ ........ ........ ........ ........
+// ^^<------------------------------------------------------------------->
+// ||                                PTS
+// | `- key frame
+//  `-- config packet
+//
+// On the wire, every packet is additionally prefixed by a single stream id
+// byte (so several streams may be interleaved over the same connection),
+// and the header's flag byte is rewritten: bit 7 is always set, and the
+// config/key flags are shifted down into bits 6 and 5.
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ByteOrder};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+const HEADER_SIZE: usize = 12;
+
+// A decoded meta header, as found in the recording files.
+#[derive(Debug, Clone, Copy)]
+pub struct MetaHeader {
+    raw: [u8; HEADER_SIZE],
+    pub pts: u64,
+    pub is_config: bool,
+    pub is_key: bool,
+    pub size: u32,
+}
+
+impl MetaHeader {
+    pub fn parse(raw: [u8; HEADER_SIZE]) -> Self {
+        let pts_and_flags = BigEndian::read_u64(&raw[..8]);
+        let pts = pts_and_flags & 0x3F_FF_FF_FF_FF_FF_FF_FF;
+        let is_config = pts_and_flags & 0x80_00_00_00_00_00_00_00 != 0;
+        let is_key = pts_and_flags & 0x40_00_00_00_00_00_00_00 != 0;
+        let size = BigEndian::read_u32(&raw[8..12]);
+
+        Self {
+            raw,
+            pts,
+            is_config,
+            is_key,
+            size,
+        }
+    }
+
+    // Rewrite the header's flag byte for the wire (see module doc).
+    pub fn to_wire_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut bytes = self.raw;
+        bytes[0] = 0x80 | ((bytes[0] & 0xC0) >> 1) | (bytes[0] & 0x1F);
+        bytes
+    }
+}
+
+// Reads meta headers and packet payloads from a recording file, distinguishing
+// a clean end-of-stream (EOF exactly at a frame boundary) from a truncated
+// file (EOF in the middle of a header or a payload), which is reported as an
+// error instead of being silently treated as the end of the stream.
+pub struct FramedFileReader {
+    reader: BufReader<File>,
+}
+
+impl FramedFileReader {
+    pub fn open(filepath: &str) -> Result<Self> {
+        let file = File::open(filepath)?;
+        Ok(Self {
+            reader: BufReader::new(file),
+        })
+    }
+
+    // Read the next meta header, or `None` on a clean EOF.
+    pub fn read_header(&mut self) -> Result<Option<MetaHeader>> {
+        let mut raw = [0; HEADER_SIZE];
+        let mut total = 0;
+        while total < raw.len() {
+            let n = self.reader.read(&mut raw[total..])?;
+            if n == 0 {
+                if total == 0 {
+                    return Ok(None);
+                }
+                bail!(
+                    "Truncated file: incomplete meta header ({total}/{} bytes)",
+                    raw.len()
+                );
+            }
+            total += n;
+        }
+        Ok(Some(MetaHeader::parse(raw)))
+    }
+
+    // Read exactly `size` bytes of packet payload.
+    pub fn read_payload(&mut self, size: u32) -> Result<Vec<u8>> {
+        let mut payload = vec![0; size as usize];
+        self.reader.read_exact(&mut payload).map_err(|_| {
+            anyhow::anyhow!("Truncated file: expected {size} bytes of packet payload")
+        })?;
+        Ok(payload)
+    }
+
+    // Skip `size` bytes of packet payload without buffering them.
+    pub fn skip_payload(&mut self, size: u32) -> Result<()> {
+        let mut remaining = size as u64;
+        let mut buf = [0; 4096];
+        while remaining > 0 {
+            let chunk = buf.len().min(remaining as usize);
+            let n = self.reader.read(&mut buf[..chunk])?;
+            if n == 0 {
+                bail!("Truncated file: expected {size} bytes of packet payload to skip");
+            }
+            remaining -= n as u64;
+        }
+        Ok(())
+    }
+}
+
+// A connection to a kymux endpoint, guaranteeing every write is either fully
+// sent or reported as an error (no silent short writes).
+pub struct KymuxConn {
+    tcp_stream: TcpStream,
+}
+
+impl KymuxConn {
+    // Resolve `host`/`port` (a hostname, an IPv4 or an IPv6 address) and
+    // connect to the first address that accepts the connection.
+    pub fn connect(host: &str, port: u16) -> Result<Self> {
+        let addrs = (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {host}:{port}"))?;
+
+        let mut last_err = None;
+        for addr in addrs {
+            match TcpStream::connect(addr) {
+                Ok(tcp_stream) => return Ok(Self { tcp_stream }),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        match last_err {
+            Some(e) => Err(e).with_context(|| format!("Failed to connect to {host}:{port}")),
+            None => bail!("{host}:{port} did not resolve to any address"),
+        }
+    }
+
+    // Select the endpoint and wait for the sync byte.
+    pub fn handshake(&mut self, endpoint_id: u16) -> Result<()> {
+        self.tcp_stream.write_all(&endpoint_id.to_be_bytes())?;
+
+        let mut sync_byte = [0; 1];
+        self.tcp_stream.read_exact(&mut sync_byte)?;
+        Ok(())
+    }
+
+    // Announce a stream's codec, to be sent once per stream right after the
+    // handshake (or again after a reconnection). The codec packet is a fixed
+    // 12 bytes (the fourcc followed by 8 reserved/padding bytes, matching
+    // the original single-stream codec packet), prefixed by the stream id
+    // byte.
+    pub fn send_codec(&mut self, sid: u8, codec: [u8; 4]) -> Result<()> {
+        self.tcp_stream.write_all(&[sid])?;
+        self.tcp_stream.write_all(&codec)?;
+        self.tcp_stream.write_all(&[0; 8])?;
+        Ok(())
+    }
+
+    // Send one interleaved packet: stream id, meta header, raw payload.
+    pub fn send_packet(&mut self, sid: u8, header: MetaHeader, payload: &[u8]) -> Result<()> {
+        self.tcp_stream.write_all(&[sid])?;
+        self.tcp_stream.write_all(&header.to_wire_bytes())?;
+        self.tcp_stream.write_all(payload)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A config, key frame with pts=0x1234 and size=0x10.
+    const RAW_HEADER: [u8; HEADER_SIZE] = [
+        0xC0, 0, 0, 0, 0, 0, 0x12, 0x34, 0, 0, 0, 0x10,
+    ];
+
+    #[test]
+    fn parse_reads_pts_flags_and_size() {
+        let header = MetaHeader::parse(RAW_HEADER);
+        assert_eq!(header.pts, 0x1234);
+        assert!(header.is_config);
+        assert!(header.is_key);
+        assert_eq!(header.size, 0x10);
+    }
+
+    #[test]
+    fn parse_clears_flag_bits_from_pts() {
+        // flags set, and the rest of byte 0 set too: only the two flag bits
+        // are cleared, the low 6 bits of byte 0 stay part of pts
+        let raw = [0xFF, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        let header = MetaHeader::parse(raw);
+        assert_eq!(header.pts, 0x3F_00_00_00_00_00_00_01);
+        assert!(header.is_config);
+        assert!(header.is_key);
+    }
+
+    #[test]
+    fn to_wire_bytes_shifts_flags_and_sets_bit7() {
+        let header = MetaHeader::parse(RAW_HEADER);
+        let wire = header.to_wire_bytes();
+        // bit 7 always set, config/key shifted down into bits 6 and 5
+        assert_eq!(wire[0], 0b1110_0000);
+        // the rest of the header (pts low bytes, size) is unchanged
+        assert_eq!(&wire[1..], &RAW_HEADER[1..]);
+    }
+
+    #[test]
+    fn non_config_non_key_header_gets_only_bit7_set_on_the_wire() {
+        let raw = [0, 0, 0, 0, 0, 0, 0, 0x7B, 0, 0, 0, 4];
+        let header = MetaHeader::parse(raw);
+        assert!(!header.is_config);
+        assert!(!header.is_key);
+        assert_eq!(header.to_wire_bytes()[0], 0x80);
+    }
+
+    fn write_temp_file(name: &str, content: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content).unwrap();
+        path
+    }
+
+    #[test]
+    fn framed_file_reader_reports_clean_eof_at_frame_boundary() {
+        let path = write_temp_file("rtstreamer_test_clean_eof.bin", &[]);
+        let mut reader = FramedFileReader::open(path.to_str().unwrap()).unwrap();
+        assert!(reader.read_header().unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn framed_file_reader_reports_truncated_header_as_error() {
+        // only 5 of the 12 header bytes are present
+        let path = write_temp_file("rtstreamer_test_truncated_header.bin", &[0; 5]);
+        let mut reader = FramedFileReader::open(path.to_str().unwrap()).unwrap();
+        assert!(reader.read_header().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn framed_file_reader_reports_truncated_payload_as_error() {
+        let mut content = RAW_HEADER.to_vec();
+        content.extend_from_slice(&[0; 4]); // size says 0x10 (16), only 4 provided
+        let path = write_temp_file("rtstreamer_test_truncated_payload.bin", &content);
+        let mut reader = FramedFileReader::open(path.to_str().unwrap()).unwrap();
+        let header = reader.read_header().unwrap().unwrap();
+        assert!(reader.read_payload(header.size).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn framed_file_reader_reads_header_then_payload() {
+        let mut content = RAW_HEADER.to_vec();
+        content.extend_from_slice(&[0xAB; 0x10]);
+        let path = write_temp_file("rtstreamer_test_header_then_payload.bin", &content);
+        let mut reader = FramedFileReader::open(path.to_str().unwrap()).unwrap();
+        let header = reader.read_header().unwrap().unwrap();
+        let payload = reader.read_payload(header.size).unwrap();
+        assert_eq!(payload, vec![0xAB; 0x10]);
+        std::fs::remove_file(path).unwrap();
+    }
+}